@@ -2,22 +2,100 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
+use clap::{Parser, Subcommand};
 use cpal::{SampleFormat, SupportedBufferSize, SupportedStreamConfig};
 use cpal::HostId::{Asio, Wasapi};
-use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use glob::glob;
 use log::{debug, error, info, warn};
+use rand::Rng;
 use rand::prelude::SliceRandom;
 use rand::thread_rng;
-use rdev::{EventType, Key, listen};
+use rdev::{Button, EventType, Key, listen};
 use rdev::EventType::KeyPress;
-use rodio::{cpal, Decoder, OutputStream, Source};
+use rodio::{cpal, Decoder, Source};
 use rodio::cpal::{BufferSize, SampleRate, StreamConfig};
-use rodio::source::Buffered;
+use rodio::source::{Buffered, UniformSourceIterator};
 use serde::{Deserialize, Serialize};
 
+mod record;
+
+#[derive(Parser)]
+#[command(author, version, about)]
+struct Cli {
+    /// Path to the config file (default: ./config.json)
+    #[arg(long)]
+    config: Option<String>,
+    /// Overrides Config::device_config.device_name and disables use_default
+    #[arg(long)]
+    device: Option<String>,
+    /// Overrides the master playback volume
+    #[arg(long)]
+    volume: Option<f32>,
+    /// Overrides the base directory sound packs are loaded from (default: ./audio)
+    #[arg(long)]
+    audio_dir: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List every host's output devices along with their supported sample rates/formats/channels
+    ListDevices,
+    /// Capture audio from an input device and auto-slice it into a sound pack
+    Record {
+        /// Directory the sliced WAV samples are written to
+        directory: String,
+    },
+}
+
+fn list_devices() {
+    for host_id in cpal::available_hosts() {
+        let host = match cpal::host_from_id(host_id) {
+            Ok(host) => host,
+            Err(error) => {
+                warn!("Couldn't open host {:?}: {}", host_id, error);
+                continue;
+            }
+        };
+
+        println!("Host: {:?}", host_id);
+
+        let devices = match host.output_devices() {
+            Ok(devices) => devices,
+            Err(error) => {
+                warn!("Couldn't enumerate output devices for {:?}: {}", host_id, error);
+                continue;
+            }
+        };
+
+        for device in devices {
+            let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+            println!("  Device: {}", name);
+
+            match device.supported_output_configs() {
+                Ok(configs) => {
+                    for config in configs {
+                        println!(
+                            "    {} channel(s), {}-{} Hz, {:?}",
+                            config.channels(),
+                            config.min_sample_rate().0,
+                            config.max_sample_rate().0,
+                            config.sample_format()
+                        );
+                    }
+                }
+                Err(error) => warn!("Couldn't query supported configs for {}: {}", name, error),
+            }
+        }
+    }
+}
+
 struct ListenState {
     key_states: HashMap<Key, bool>,
 }
@@ -30,32 +108,546 @@ impl ListenState {
     }
 }
 
-fn get_buffered_sounds_from_directory(directory: &str) -> Vec<Buffered<Decoder<BufReader<File>>>> {
+/// The sound formats loaded when `Config::sound_extensions` isn't set. `rodio::Decoder`
+/// auto-detects the actual codec from the file contents, so these are just the extensions
+/// globbed for.
+fn default_sound_extensions() -> Vec<String> {
+    ["wav", "mp3", "flac", "ogg"]
+        .iter()
+        .map(|extension| extension.to_string())
+        .collect()
+}
+
+fn get_buffered_sounds_from_directory(
+    directory: &str,
+    extensions: &[String],
+) -> Vec<Buffered<Decoder<BufReader<File>>>> {
     let mut sounds = Vec::new();
 
-    let full_glob = directory.to_owned() + "/*.wav";
+    for extension in extensions {
+        let full_glob = format!("{}/*.{}", directory, extension);
+
+        debug!("Full glob: {}", full_glob);
+
+        for entry in glob(&full_glob).expect("Invalid glob pattern") {
+            match entry {
+                Ok(path) => {
+                    debug!("Found file: {:?}", path);
+
+                    let file = match File::open(&path) {
+                        Ok(file) => BufReader::new(file),
+                        Err(error) => {
+                            error!("Couldn't open {:?}: {}", path, error);
+                            continue;
+                        }
+                    };
+
+                    match Decoder::new(file) {
+                        Ok(decoder) => sounds.push(decoder.buffered()),
+                        Err(error) => {
+                            error!("Couldn't decode {:?}, skipping: {}", path, error);
+                        }
+                    }
+                }
+                Err(error) => {
+                    println!("Glob Error: {}", error);
+                }
+            }
+        }
+    }
 
-    debug!("Full glob: {}", full_glob);
+    sounds
+}
 
-    for entry in glob(&full_glob).expect("Invalid glob pattern") {
-        match entry {
-            Ok(path) => {
-                debug!("Found file: {:?}", path);
+/// Maps the `rdev::Key` variant names accepted in a `key_sounds` config entry (e.g. `"Space"`,
+/// `"Return"`) to the corresponding `Key`. Returns `None` for unrecognized names.
+fn key_name_to_key(name: &str) -> Option<Key> {
+    Some(match name {
+        "Alt" => Key::Alt,
+        "AltGr" => Key::AltGr,
+        "Backspace" => Key::Backspace,
+        "CapsLock" => Key::CapsLock,
+        "ControlLeft" => Key::ControlLeft,
+        "ControlRight" => Key::ControlRight,
+        "Delete" => Key::Delete,
+        "DownArrow" => Key::DownArrow,
+        "End" => Key::End,
+        "Escape" => Key::Escape,
+        "F1" => Key::F1,
+        "F2" => Key::F2,
+        "F3" => Key::F3,
+        "F4" => Key::F4,
+        "F5" => Key::F5,
+        "F6" => Key::F6,
+        "F7" => Key::F7,
+        "F8" => Key::F8,
+        "F9" => Key::F9,
+        "F10" => Key::F10,
+        "F11" => Key::F11,
+        "F12" => Key::F12,
+        "Home" => Key::Home,
+        "LeftArrow" => Key::LeftArrow,
+        "MetaLeft" => Key::MetaLeft,
+        "MetaRight" => Key::MetaRight,
+        "PageDown" => Key::PageDown,
+        "PageUp" => Key::PageUp,
+        "Return" => Key::Return,
+        "RightArrow" => Key::RightArrow,
+        "ShiftLeft" => Key::ShiftLeft,
+        "ShiftRight" => Key::ShiftRight,
+        "Space" => Key::Space,
+        "Tab" => Key::Tab,
+        "UpArrow" => Key::UpArrow,
+        "PrintScreen" => Key::PrintScreen,
+        "ScrollLock" => Key::ScrollLock,
+        "Pause" => Key::Pause,
+        "NumLock" => Key::NumLock,
+        "BackQuote" => Key::BackQuote,
+        "Num1" => Key::Num1,
+        "Num2" => Key::Num2,
+        "Num3" => Key::Num3,
+        "Num4" => Key::Num4,
+        "Num5" => Key::Num5,
+        "Num6" => Key::Num6,
+        "Num7" => Key::Num7,
+        "Num8" => Key::Num8,
+        "Num9" => Key::Num9,
+        "Num0" => Key::Num0,
+        "Minus" => Key::Minus,
+        "Equal" => Key::Equal,
+        "KeyQ" => Key::KeyQ,
+        "KeyW" => Key::KeyW,
+        "KeyE" => Key::KeyE,
+        "KeyR" => Key::KeyR,
+        "KeyT" => Key::KeyT,
+        "KeyY" => Key::KeyY,
+        "KeyU" => Key::KeyU,
+        "KeyI" => Key::KeyI,
+        "KeyO" => Key::KeyO,
+        "KeyP" => Key::KeyP,
+        "LeftBracket" => Key::LeftBracket,
+        "RightBracket" => Key::RightBracket,
+        "KeyA" => Key::KeyA,
+        "KeyS" => Key::KeyS,
+        "KeyD" => Key::KeyD,
+        "KeyF" => Key::KeyF,
+        "KeyG" => Key::KeyG,
+        "KeyH" => Key::KeyH,
+        "KeyJ" => Key::KeyJ,
+        "KeyK" => Key::KeyK,
+        "KeyL" => Key::KeyL,
+        "SemiColon" => Key::SemiColon,
+        "Quote" => Key::Quote,
+        "BackSlash" => Key::BackSlash,
+        "IntlBackslash" => Key::IntlBackslash,
+        "KeyZ" => Key::KeyZ,
+        "KeyX" => Key::KeyX,
+        "KeyC" => Key::KeyC,
+        "KeyV" => Key::KeyV,
+        "KeyB" => Key::KeyB,
+        "KeyN" => Key::KeyN,
+        "KeyM" => Key::KeyM,
+        "Comma" => Key::Comma,
+        "Dot" => Key::Dot,
+        "Slash" => Key::Slash,
+        "Insert" => Key::Insert,
+        "KpReturn" => Key::KpReturn,
+        "KpMinus" => Key::KpMinus,
+        "KpPlus" => Key::KpPlus,
+        "KpMultiply" => Key::KpMultiply,
+        "KpDivide" => Key::KpDivide,
+        "Kp0" => Key::Kp0,
+        "Kp1" => Key::Kp1,
+        "Kp2" => Key::Kp2,
+        "Kp3" => Key::Kp3,
+        "Kp4" => Key::Kp4,
+        "Kp5" => Key::Kp5,
+        "Kp6" => Key::Kp6,
+        "Kp7" => Key::Kp7,
+        "Kp8" => Key::Kp8,
+        "Kp9" => Key::Kp9,
+        "KpDelete" => Key::KpDelete,
+        "Function" => Key::Function,
+        _ => return None,
+    })
+}
 
-                let file = BufReader::new(File::open(path).unwrap());
+/// Maps the `rdev::Button` variant names accepted in a `button_sounds` config entry
+/// (`"Left"`, `"Right"`, `"Middle"`) to the corresponding `Button`.
+fn button_name_to_button(name: &str) -> Option<Button> {
+    Some(match name {
+        "Left" => Button::Left,
+        "Right" => Button::Right,
+        "Middle" => Button::Middle,
+        _ => return None,
+    })
+}
 
-                sounds.push(Decoder::new(file).unwrap().buffered());
-            }
-            Err(error) => {
-                println!("Glob Error: {}", error);
-            }
+/// Picks a random sound from the per-key/button bucket if one is mapped, otherwise falls back
+/// to the generic pool for the event type.
+fn choose_sound<'a, K: Eq + std::hash::Hash>(
+    buckets: &'a HashMap<K, Vec<Buffered<Decoder<BufReader<File>>>>>,
+    key: &K,
+    fallback: &'a [Buffered<Decoder<BufReader<File>>>],
+) -> Option<&'a Buffered<Decoder<BufReader<File>>>> {
+    buckets
+        .get(key)
+        .map(Vec::as_slice)
+        .unwrap_or(fallback)
+        .choose(&mut thread_rng())
+}
+
+/// Per-event volume overrides. An unset event falls back to `Config::volume`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EventVolumes {
+    key_down: Option<f32>,
+    key_up: Option<f32>,
+    mouse_down: Option<f32>,
+    mouse_up: Option<f32>,
+}
+
+/// Resolves the volume to play an event at: its own override, then the master volume, then full
+/// volume.
+fn effective_volume(master_volume: Option<f32>, event_volume: Option<f32>) -> f32 {
+    event_volume.or(master_volume).unwrap_or(1.0)
+}
+
+/// Validates a `Config::pitch_jitter` range for use with `gen_range`, swapping it if it's
+/// reversed and rejecting it (returning `None`) if it has no span, e.g. `(1.0, 1.0)`, or either
+/// bound is NaN.
+fn resolve_pitch_jitter_range(range: Option<(f32, f32)>) -> Option<(f32, f32)> {
+    range.and_then(|(min, max)| match min.partial_cmp(&max) {
+        Some(std::cmp::Ordering::Less) => Some((min, max)),
+        Some(std::cmp::Ordering::Greater) => Some((max, min)),
+        _ => None,
+    })
+}
+
+/// Builds a one-shot playback source from `sound`, applying `volume` and, if `pitch_jitter` is
+/// set, a random speed factor drawn from that `(min, max)` range so repeated plays of the same
+/// sample don't sound identical.
+fn build_source(
+    sound: &Buffered<Decoder<BufReader<File>>>,
+    volume: f32,
+    pitch_jitter: Option<(f32, f32)>,
+) -> impl Source<Item = f32> + Send {
+    let valid_range = resolve_pitch_jitter_range(pitch_jitter);
+
+    if pitch_jitter.is_some() && valid_range.is_none() {
+        warn!(
+            "pitch_jitter range {:?} has no span, skipping jitter",
+            pitch_jitter
+        );
+    }
+
+    let speed = valid_range
+        .map(|(min, max)| thread_rng().gen_range(min..max))
+        .unwrap_or(1.0);
+
+    sound.clone().convert_samples().speed(speed).amplify(volume)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_pitch_jitter_range_passes_through_a_normal_range() {
+        assert_eq!(resolve_pitch_jitter_range(Some((0.97, 1.03))), Some((0.97, 1.03)));
+    }
+
+    #[test]
+    fn resolve_pitch_jitter_range_swaps_a_reversed_range() {
+        assert_eq!(resolve_pitch_jitter_range(Some((1.03, 0.97))), Some((0.97, 1.03)));
+    }
+
+    #[test]
+    fn resolve_pitch_jitter_range_rejects_a_zero_span_range() {
+        assert_eq!(resolve_pitch_jitter_range(Some((1.0, 1.0))), None);
+    }
+
+    #[test]
+    fn resolve_pitch_jitter_range_rejects_nan_bounds() {
+        assert_eq!(resolve_pitch_jitter_range(Some((f32::NAN, 1.0))), None);
+        assert_eq!(resolve_pitch_jitter_range(Some((0.97, f32::NAN))), None);
+    }
+
+    #[test]
+    fn resolve_pitch_jitter_range_passes_through_none() {
+        assert_eq!(resolve_pitch_jitter_range(None), None);
+    }
+}
+
+/// An output stream built directly on `cpal` (instead of `rodio::OutputStream`) so we can install
+/// our own error callback: rodio's own callback just `eprintln!`s and never surfaces the error to
+/// `OutputStreamHandle::play_raw`, which only fails if the `OutputStream` itself has been dropped.
+/// That meant the old "rebuild on playback failure" logic could never actually trigger on a real
+/// device disconnect. `errored` is flipped by cpal's error callback (e.g. on device unplug) and
+/// checked by `play_sound` before every play.
+struct ManagedOutputStream {
+    _stream: cpal::Stream,
+    queue: Arc<Mutex<Vec<Box<dyn Iterator<Item = f32> + Send>>>>,
+    channels: u16,
+    sample_rate: u32,
+    errored: Arc<AtomicBool>,
+}
+
+impl ManagedOutputStream {
+    /// Queues `source` for mixing into the stream's next output callbacks.
+    fn play(&self, source: impl Source<Item = f32> + Send + 'static) {
+        let resampled = UniformSourceIterator::<_, f32>::new(source, self.channels, self.sample_rate);
+        self.queue.lock().unwrap().push(Box::new(resampled));
+    }
+
+    /// Returns whether the stream's error callback has fired since the last check, clearing the
+    /// flag.
+    fn has_errored(&self) -> bool {
+        self.errored.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// Parses a `device_config.format` string (`"f32"`, `"i16"`, ...) into a `cpal::SampleFormat`.
+fn parse_sample_format(format: &str) -> Result<SampleFormat, String> {
+    match format.to_lowercase().as_str() {
+        "i8" => Ok(SampleFormat::I8),
+        "i16" => Ok(SampleFormat::I16),
+        "i32" => Ok(SampleFormat::I32),
+        "i64" => Ok(SampleFormat::I64),
+        "u8" => Ok(SampleFormat::U8),
+        "u16" => Ok(SampleFormat::U16),
+        "u32" => Ok(SampleFormat::U32),
+        "u64" => Ok(SampleFormat::U64),
+        "f32" => Ok(SampleFormat::F32),
+        "f64" => Ok(SampleFormat::F64),
+        other => Err(format!("Invalid sample format: {}", other)),
+    }
+}
+
+/// Finds the output device named `device_name`, searching `host_name`'s devices if given
+/// (`"asio"`/`"wasapi"`) or the system default host otherwise. Not requiring a host lets
+/// `--device` pick a device by name alone, without a fully filled-in `device_config`.
+fn find_output_device(host_name: Option<&str>, device_name: &str) -> Result<cpal::Device, String> {
+    let host = match host_name {
+        Some(host_name) => {
+            let host_id = match host_name.to_lowercase().as_str() {
+                "asio" => Asio,
+                "wasapi" => Wasapi,
+                _ => return Err(format!("Invalid host: {}", host_name)),
+            };
+
+            cpal::host_from_id(host_id)
+                .map_err(|error| format!("Couldn't open host: {}", error))?
         }
+        None => cpal::default_host(),
+    };
+
+    host.output_devices()
+        .map_err(|error| format!("Couldn't enumerate output devices: {}", error))?
+        .find(|device| device.name().map(|name| name == device_name).unwrap_or(false))
+        .ok_or_else(|| format!("Couldn't find device {}", device_name))
+}
+
+/// Resolves the device/stream config described by `config.device_config` (or, if
+/// `config.use_default` is set, whatever cpal reports as the default output device's config),
+/// without actually building the stream yet.
+///
+/// Only `device_name` is required to pick a non-default device: if `num_channels`, `sample_rate`,
+/// `format` and `buffer_size` are all filled in, they're used verbatim (e.g. to force a specific
+/// buffer size), otherwise the device's own default output config is used, the same way
+/// `default_output_device_config` resolves the default device's config. This keeps a bare
+/// `--device "My DAC"` override useful without requiring the rest of `device_config` to be
+/// hand-filled first.
+fn resolve_output_device_config(config: &Config) -> Result<(cpal::Device, StreamConfig, SampleFormat), String> {
+    if config.use_default {
+        return default_output_device_config();
     }
 
-    sounds
+    let device_name = config
+        .device_config
+        .device_name
+        .clone()
+        .ok_or("Device name not specified")?;
+
+    let device = find_output_device(config.device_config.host.as_deref(), &device_name)?;
+
+    let (stream_config, sample_format) = match (
+        config.device_config.num_channels,
+        config.device_config.sample_rate,
+        config.device_config.format.clone(),
+        config.device_config.buffer_size,
+    ) {
+        (Some(num_channels), Some(sample_rate), Some(format), Some(buffer_size)) => {
+            let sample_format = parse_sample_format(&format)?;
+            let desired_stream_config = SupportedStreamConfig::new(
+                num_channels,
+                SampleRate(sample_rate),
+                SupportedBufferSize::Range {
+                    min: buffer_size,
+                    max: buffer_size,
+                },
+                sample_format,
+            );
+
+            let mut stream_config: StreamConfig = desired_stream_config.into();
+            stream_config.buffer_size = BufferSize::Fixed(buffer_size);
+
+            (stream_config, sample_format)
+        }
+        _ => {
+            let supported_config = device
+                .default_output_config()
+                .map_err(|error| format!("No supported output config for {}: {}", device_name, error))?;
+
+            let sample_format = supported_config.sample_format();
+            (supported_config.into(), sample_format)
+        }
+    };
+
+    Ok((device, stream_config, sample_format))
+}
+
+/// Resolves the system default output device's own config, the same way `record::run` resolves
+/// the default input device's config.
+fn default_output_device_config() -> Result<(cpal::Device, StreamConfig, SampleFormat), String> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or("No default output device available")?;
+
+    let supported_config = device
+        .default_output_config()
+        .map_err(|error| format!("No supported output config for default device: {}", error))?;
+
+    let sample_format = supported_config.sample_format();
+    let stream_config: StreamConfig = supported_config.into();
+
+    Ok((device, stream_config, sample_format))
+}
+
+/// Builds the cpal stream itself: a queue of in-flight sources that get mixed down sample-by-
+/// sample in the output callback, and an error callback that flips `errored` instead of rodio's
+/// hard-coded `eprintln!`.
+fn build_managed_output_stream(
+    device: &cpal::Device,
+    stream_config: &StreamConfig,
+    sample_format: SampleFormat,
+) -> Result<ManagedOutputStream, String> {
+    let queue: Arc<Mutex<Vec<Box<dyn Iterator<Item = f32> + Send>>>> = Arc::new(Mutex::new(Vec::new()));
+    let queue_for_callback = queue.clone();
+
+    let errored = Arc::new(AtomicBool::new(false));
+    let errored_for_callback = errored.clone();
+
+    let err_fn = move |error| {
+        error!("Output stream error: {}", error);
+        errored_for_callback.store(true, Ordering::SeqCst);
+    };
+
+    let stream = match sample_format {
+        SampleFormat::F32 => device.build_output_stream(
+            stream_config,
+            move |data: &mut [f32], _| {
+                for sample in data.iter_mut() {
+                    *sample = mix_next_sample(&queue_for_callback);
+                }
+            },
+            err_fn,
+            None,
+        ),
+        SampleFormat::I16 => device.build_output_stream(
+            stream_config,
+            move |data: &mut [i16], _| {
+                for sample in data.iter_mut() {
+                    *sample = (mix_next_sample(&queue_for_callback) * i16::MAX as f32) as i16;
+                }
+            },
+            err_fn,
+            None,
+        ),
+        SampleFormat::U16 => device.build_output_stream(
+            stream_config,
+            move |data: &mut [u16], _| {
+                for sample in data.iter_mut() {
+                    let normalized = (mix_next_sample(&queue_for_callback) + 1.0) / 2.0;
+                    *sample = (normalized * u16::MAX as f32) as u16;
+                }
+            },
+            err_fn,
+            None,
+        ),
+        _ => return Err(format!("Unsupported output sample format: {:?}", sample_format)),
+    }
+    .map_err(|error| format!("Couldn't build output stream: {}", error))?;
+
+    stream
+        .play()
+        .map_err(|error| format!("Couldn't start output stream: {}", error))?;
+
+    Ok(ManagedOutputStream {
+        _stream: stream,
+        queue,
+        channels: stream_config.channels,
+        sample_rate: stream_config.sample_rate.0,
+        errored,
+    })
+}
+
+/// Pulls and sums one sample from every in-flight source in `queue`, dropping any source that's
+/// run out. Called once per output sample from the cpal callback.
+fn mix_next_sample(queue: &Mutex<Vec<Box<dyn Iterator<Item = f32> + Send>>>) -> f32 {
+    let mut queue_lock = queue.lock().unwrap();
+    let mut mixed = 0.0;
+
+    queue_lock.retain_mut(|source| match source.next() {
+        Some(sample) => {
+            mixed += sample;
+            true
+        }
+        None => false,
+    });
+
+    mixed
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Selects the output device described by `config.device_config`, returning an error instead of
+/// panicking when the configured host/device/format isn't available.
+fn build_output_stream(config: &Config) -> Result<ManagedOutputStream, String> {
+    let (device, stream_config, sample_format) = resolve_output_device_config(config)?;
+    build_managed_output_stream(&device, &stream_config, sample_format)
+}
+
+/// Builds the configured output stream, falling back to the system default device if the
+/// configured host/device/format isn't available.
+fn build_output_stream_or_fallback(config: &Config) -> ManagedOutputStream {
+    build_output_stream(config).unwrap_or_else(|error| {
+        warn!("Falling back to default output device: {}", error);
+        let (device, stream_config, sample_format) =
+            default_output_device_config().expect("No default output device available either");
+        build_managed_output_stream(&device, &stream_config, sample_format)
+            .expect("Couldn't build fallback output stream")
+    })
+}
+
+/// Plays `sound` through the shared output stream, rebuilding it (falling back to the default
+/// device if necessary) if the stream's error callback has fired since the last play, e.g.
+/// because the device was unplugged.
+fn play_sound(
+    output_stream: &Arc<Mutex<ManagedOutputStream>>,
+    config: &Config,
+    sound: &Buffered<Decoder<BufReader<File>>>,
+    volume: f32,
+    pitch_jitter: Option<(f32, f32)>,
+) {
+    let mut output_stream_lock = output_stream.lock().unwrap();
+
+    if output_stream_lock.has_errored() {
+        warn!("Output stream reported an error, rebuilding it...");
+        *output_stream_lock = build_output_stream_or_fallback(config);
+    }
+
+    output_stream_lock.play(build_source(sound, volume, pitch_jitter));
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct DeviceConfig {
     host: Option<String>,
     device_name: Option<String>,
@@ -65,10 +657,29 @@ struct DeviceConfig {
     format: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Config {
     use_default: bool,
     device_config: DeviceConfig,
+    /// Maps `rdev::Key` variant names (e.g. `"Space"`, `"Return"`) to a sound directory that
+    /// overrides the generic keydown/keyup pools for that key. Keys with no entry here fall
+    /// back to the generic pools; mapping a key to a directory with no files plays nothing.
+    key_sounds: Option<HashMap<String, String>>,
+    /// Same as `key_sounds` but for `rdev::Button` variant names (`"Left"`, `"Right"`, `"Middle"`).
+    button_sounds: Option<HashMap<String, String>>,
+    /// File extensions globbed for when loading a sound directory. Defaults to
+    /// `["wav", "mp3", "flac", "ogg"]`; `rodio::Decoder` auto-detects the actual codec.
+    sound_extensions: Option<Vec<String>>,
+    /// Base directory the keydown/keyup/mousedown/mouseup pools are loaded from. Defaults to
+    /// `./audio`.
+    audio_directory: Option<String>,
+    /// Master playback volume multiplier. Defaults to full volume (1.0).
+    volume: Option<f32>,
+    /// Per-event volume overrides; an event with an override plays at it instead of `volume`.
+    event_volumes: Option<EventVolumes>,
+    /// `(min, max)` speed factor range each play's pitch is randomly drawn from, e.g.
+    /// `(0.97, 1.03)`. Unset means no pitch jitter.
+    pitch_jitter: Option<(f32, f32)>,
 }
 
 impl Config {
@@ -83,11 +694,45 @@ impl Config {
                 buffer_size: None,
                 format: None,
             },
+            key_sounds: None,
+            button_sounds: None,
+            sound_extensions: None,
+            audio_directory: None,
+            volume: None,
+            event_volumes: None,
+            pitch_jitter: None,
+        }
+    }
+}
+
+/// Loads the directories configured in `key_sounds`/`button_sounds` into per-key/button sound
+/// buckets, logging and skipping any name that doesn't match a known `Key`/`Button` variant.
+fn load_named_sound_buckets<K: Eq + std::hash::Hash>(
+    mapping: &Option<HashMap<String, String>>,
+    extensions: &[String],
+    name_to_key: impl Fn(&str) -> Option<K>,
+) -> HashMap<K, Vec<Buffered<Decoder<BufReader<File>>>>> {
+    let mut buckets = HashMap::new();
+
+    if let Some(mapping) = mapping {
+        for (name, directory) in mapping {
+            match name_to_key(name) {
+                Some(key) => {
+                    buckets.insert(key, get_buffered_sounds_from_directory(directory, extensions));
+                }
+                None => {
+                    warn!("Unknown key/button name in config: {}", name);
+                }
+            }
         }
     }
+
+    buckets
 }
 
 fn main() {
+    let cli = Cli::parse();
+
     // Initialize logger
     stderrlog::new()
         .module(module_path!())
@@ -95,11 +740,16 @@ fn main() {
         .init()
         .expect("Failed to initialize stderrlog");
 
+    if matches!(cli.command, Some(Command::ListDevices)) {
+        list_devices();
+        return;
+    }
+
     info!("Loading config...");
 
-    let file_path = Path::new("./config.json");
+    let file_path = Path::new(cli.config.as_deref().unwrap_or("./config.json"));
 
-    let config = match file_path.exists() {
+    let mut config: Config = match file_path.exists() {
         true => {
             info!("Config file found!");
             let file = File::open(file_path).expect("Cannot open config file despite it existing");
@@ -115,90 +765,51 @@ fn main() {
         }
     };
 
-    let (_stream, stream_handle) = match config.use_default {
-        true => OutputStream::try_default().unwrap(),
-        false => {
-            let device = cpal::host_from_id(
-                match config
-                    .device_config
-                    .host
-                    .expect("Host not specified")
-                    .to_lowercase()
-                    .as_str()
-                {
-                    "asio" => Asio,
-                    "wasapi" => Wasapi,
-                    _ => {
-                        panic!("Invalid host");
-                    }
-                },
-            )
-            .unwrap()
-            .output_devices()
-            .unwrap()
-            .find(|device| {
-                device.name().unwrap()
-                    == config
-                        .device_config
-                        .device_name
-                        .clone()
-                        .expect("Device name not specified")
-            })
-            .expect("Couldn't find device");
-
-            let buffer_size = config
-                .device_config
-                .buffer_size
-                .expect("Buffer size not specified");
-
-            // Parsing config
-            let desired_stream_config = SupportedStreamConfig::new(
-                config
-                    .device_config
-                    .num_channels
-                    .expect("Number of channels not specified"),
-                SampleRate(
-                    config
-                        .device_config
-                        .sample_rate
-                        .expect("Sample rate not specified"),
-                ),
-                SupportedBufferSize::Range {
-                    min: buffer_size,
-                    max: buffer_size,
-                },
-                match config
-                    .device_config
-                    .format
-                    .expect("Sample format not specifed")
-                    .to_lowercase()
-                    .as_str()
-                {
-                    "i8" => SampleFormat::I8,
-                    "i16" => SampleFormat::I16,
-                    "i32" => SampleFormat::I32,
-                    "i64" => SampleFormat::I64,
-                    "u8" => SampleFormat::U8,
-                    "u16" => SampleFormat::U16,
-                    "u32" => SampleFormat::U32,
-                    "u64" => SampleFormat::U64,
-                    "f32" => SampleFormat::F32,
-                    "f64" => SampleFormat::F64,
-                    _ => {
-                        panic!("Invalid sample format");
-                    }
-                },
-            );
+    if let Some(device) = cli.device {
+        config.device_config.device_name = Some(device);
+        config.use_default = false;
+    }
 
-            OutputStream::try_from_device_config(&device, desired_stream_config).unwrap()
-        }
-    };
+    if let Some(volume) = cli.volume {
+        config.volume = Some(volume);
+    }
+
+    if let Some(audio_dir) = cli.audio_dir {
+        config.audio_directory = Some(audio_dir);
+    }
+
+    if let Some(Command::Record { directory }) = cli.command {
+        record::run(&config, &directory);
+        return;
+    }
+
+    let config_for_reconnect = config.clone();
+    let output_stream = Arc::new(Mutex::new(build_output_stream_or_fallback(&config)));
 
     // Load audio into memory
-    let key_down_sounds = get_buffered_sounds_from_directory("./audio/keydown");
-    let key_up_sounds = get_buffered_sounds_from_directory("./audio/keyup");
-    let mouse_down_sounds = get_buffered_sounds_from_directory("./audio/mousedown");
-    let mouse_up_sounds = get_buffered_sounds_from_directory("./audio/mouseup");
+    let sound_extensions = config
+        .sound_extensions
+        .clone()
+        .unwrap_or_else(default_sound_extensions);
+
+    let audio_directory = config.audio_directory.clone().unwrap_or_else(|| "./audio".to_string());
+
+    let key_down_sounds = get_buffered_sounds_from_directory(
+        &format!("{}/keydown", audio_directory),
+        &sound_extensions,
+    );
+    let key_up_sounds = get_buffered_sounds_from_directory(
+        &format!("{}/keyup", audio_directory),
+        &sound_extensions,
+    );
+    let mouse_down_sounds = get_buffered_sounds_from_directory(
+        &format!("{}/mousedown", audio_directory),
+        &sound_extensions,
+    );
+    let mouse_up_sounds = get_buffered_sounds_from_directory(
+        &format!("{}/mouseup", audio_directory),
+        &sound_extensions,
+    );
 
     if key_down_sounds.is_empty() {
         error!("No sounds in keydown folder");
@@ -220,6 +831,26 @@ fn main() {
         return;
     }
 
+    let key_sound_buckets =
+        load_named_sound_buckets(&config.key_sounds, &sound_extensions, key_name_to_key);
+    let button_sound_buckets = load_named_sound_buckets(
+        &config.button_sounds,
+        &sound_extensions,
+        button_name_to_button,
+    );
+
+    let pitch_jitter = config.pitch_jitter;
+    let event_volumes = config.event_volumes.unwrap_or(EventVolumes {
+        key_down: None,
+        key_up: None,
+        mouse_down: None,
+        mouse_up: None,
+    });
+    let key_down_volume = effective_volume(config.volume, event_volumes.key_down);
+    let key_up_volume = effective_volume(config.volume, event_volumes.key_up);
+    let mouse_down_volume = effective_volume(config.volume, event_volumes.mouse_down);
+    let mouse_up_volume = effective_volume(config.volume, event_volumes.mouse_up);
+
     let listen_state = Arc::new(Mutex::new(ListenState::new()));
 
     if let Err(error) = listen(move |event| {
@@ -231,19 +862,17 @@ fn main() {
             KeyPress(key) => match listen_state_lock.key_states.get_mut(&key) {
                 Some(key_is_pressed) => {
                     if !*key_is_pressed {
-                        let sound = key_down_sounds.choose(&mut thread_rng()).unwrap();
-                        stream_handle
-                            .play_raw(sound.clone().convert_samples())
-                            .unwrap();
+                        if let Some(sound) = choose_sound(&key_sound_buckets, &key, &key_down_sounds) {
+                            play_sound(&output_stream, &config_for_reconnect, sound, key_down_volume, pitch_jitter);
+                        }
 
                         *key_is_pressed = true;
                     }
                 }
                 None => {
-                    let sound = key_down_sounds.choose(&mut thread_rng()).unwrap();
-                    stream_handle
-                        .play_raw(sound.clone().convert_samples())
-                        .unwrap();
+                    if let Some(sound) = choose_sound(&key_sound_buckets, &key, &key_down_sounds) {
+                        play_sound(&output_stream, &config_for_reconnect, sound, key_down_volume, pitch_jitter);
+                    }
 
                     listen_state_lock.key_states.insert(key, true);
                 }
@@ -251,34 +880,30 @@ fn main() {
             EventType::KeyRelease(key) => match listen_state_lock.key_states.get_mut(&key) {
                 Some(key_is_pressed) => {
                     if *key_is_pressed {
-                        let sound = key_up_sounds.choose(&mut thread_rng()).unwrap();
-                        stream_handle
-                            .play_raw(sound.clone().convert_samples())
-                            .unwrap();
+                        if let Some(sound) = choose_sound(&key_sound_buckets, &key, &key_up_sounds) {
+                            play_sound(&output_stream, &config_for_reconnect, sound, key_up_volume, pitch_jitter);
+                        }
 
                         *key_is_pressed = false;
                     }
                 }
                 None => {
-                    let sound = key_up_sounds.choose(&mut thread_rng()).unwrap();
-                    stream_handle
-                        .play_raw(sound.clone().convert_samples())
-                        .unwrap();
+                    if let Some(sound) = choose_sound(&key_sound_buckets, &key, &key_up_sounds) {
+                        play_sound(&output_stream, &config_for_reconnect, sound, key_up_volume, pitch_jitter);
+                    }
 
                     listen_state_lock.key_states.insert(key, false);
                 }
             },
             EventType::ButtonPress(button) => {
-                let sound = mouse_down_sounds.choose(&mut thread_rng()).unwrap();
-                stream_handle
-                    .play_raw(sound.clone().convert_samples())
-                    .unwrap();
+                if let Some(sound) = choose_sound(&button_sound_buckets, &button, &mouse_down_sounds) {
+                    play_sound(&output_stream, &config_for_reconnect, sound, mouse_down_volume, pitch_jitter);
+                }
             }
             EventType::ButtonRelease(button) => {
-                let sound = mouse_up_sounds.choose(&mut thread_rng()).unwrap();
-                stream_handle
-                    .play_raw(sound.clone().convert_samples())
-                    .unwrap();
+                if let Some(sound) = choose_sound(&button_sound_buckets, &button, &mouse_up_sounds) {
+                    play_sound(&output_stream, &config_for_reconnect, sound, mouse_up_volume, pitch_jitter);
+                }
             }
             _ => {}
         }
@@ -0,0 +1,357 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cpal::HostId::{Asio, Wasapi};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{SampleFormat, StreamConfig};
+use hound::{WavSpec, WavWriter};
+use log::{error, info};
+
+use crate::Config;
+
+/// How long a single `record` session captures audio for before slicing it into samples.
+const RECORD_SECONDS: u64 = 10;
+/// Width of the short-time energy envelope hops used for onset detection.
+const ENVELOPE_HOP_MS: f32 = 10.0;
+/// An onset fires once a hop's energy exceeds the noise floor by this factor.
+const ONSET_THRESHOLD_MULTIPLIER: f32 = 6.0;
+/// How far before a detected onset each slice starts.
+const PRE_ONSET_MS: f32 = 5.0;
+/// Maximum length of a slice measured from the onset.
+const MAX_SLICE_MS: f32 = 120.0;
+/// Target peak amplitude (dBFS) each slice is normalized to.
+const TARGET_PEAK_DBFS: f32 = -1.0;
+
+/// Captures `RECORD_SECONDS` of audio from the configured input device, slices out one sample
+/// per detected onset, and writes each slice as its own WAV file into `output_directory`.
+pub(crate) fn run(config: &Config, output_directory: &str) {
+    let device = select_input_device(config);
+
+    let supported_config = device
+        .default_input_config()
+        .expect("No supported input config for device");
+
+    let sample_format = supported_config.sample_format();
+    let sample_rate = supported_config.sample_rate().0 as f32;
+    let channels = supported_config.channels();
+    let stream_config: StreamConfig = supported_config.into();
+
+    let buffer = Arc::new(Mutex::new(Vec::<f32>::new()));
+    let buffer_for_stream = buffer.clone();
+
+    let err_fn = |error| error!("Input stream error: {}", error);
+
+    // The device hands us interleaved frames (`L,R,L,R,...` for stereo); downmix each frame to a
+    // single mono sample before buffering so the rest of the pipeline (and the WAV we write at
+    // the end) can stay mono regardless of how many channels the device actually captures with.
+    let stream = match sample_format {
+        SampleFormat::F32 => device.build_input_stream(
+            &stream_config,
+            move |data: &[f32], _| {
+                buffer_for_stream
+                    .lock()
+                    .unwrap()
+                    .extend(downmix_to_mono(data, channels));
+            },
+            err_fn,
+            None,
+        ),
+        SampleFormat::I16 => device.build_input_stream(
+            &stream_config,
+            move |data: &[i16], _| {
+                let samples: Vec<f32> = data.iter().map(|sample| *sample as f32 / i16::MAX as f32).collect();
+                buffer_for_stream
+                    .lock()
+                    .unwrap()
+                    .extend(downmix_to_mono(&samples, channels));
+            },
+            err_fn,
+            None,
+        ),
+        SampleFormat::U16 => device.build_input_stream(
+            &stream_config,
+            move |data: &[u16], _| {
+                let samples: Vec<f32> = data
+                    .iter()
+                    .map(|sample| *sample as f32 / u16::MAX as f32 * 2.0 - 1.0)
+                    .collect();
+                buffer_for_stream
+                    .lock()
+                    .unwrap()
+                    .extend(downmix_to_mono(&samples, channels));
+            },
+            err_fn,
+            None,
+        ),
+        _ => panic!("Unsupported input sample format: {:?}", sample_format),
+    }
+    .expect("Failed to build input stream");
+
+    stream.play().expect("Failed to start input stream");
+
+    info!(
+        "Recording for {} seconds, play your keyboard now...",
+        RECORD_SECONDS
+    );
+
+    std::thread::sleep(Duration::from_secs(RECORD_SECONDS));
+
+    drop(stream);
+
+    let samples = buffer.lock().unwrap().clone();
+    let slices = slice_onsets(&samples, sample_rate);
+
+    info!(
+        "Found {} onsets, writing samples to {}",
+        slices.len(),
+        output_directory
+    );
+
+    fs::create_dir_all(output_directory).expect("Couldn't create output directory");
+
+    for (index, slice) in slices.iter().enumerate() {
+        let path = Path::new(output_directory).join(format!("sample_{}.wav", index));
+        write_wav(&path, slice, sample_rate as u32);
+    }
+}
+
+/// Averages every `channels` consecutive interleaved samples in `frames` down to one mono sample
+/// per frame.
+fn downmix_to_mono(frames: &[f32], channels: u16) -> Vec<f32> {
+    if channels <= 1 {
+        return frames.to_vec();
+    }
+
+    frames
+        .chunks(channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// Picks an input device from `Config::device_config`'s `host` and `device_name`, or the default
+/// input device if `use_default` is set. Unlike output device selection in `main`, `host` is
+/// required here and there is no fallback: a missing or unknown host or device panics.
+fn select_input_device(config: &Config) -> cpal::Device {
+    match config.use_default {
+        true => cpal::default_host()
+            .default_input_device()
+            .expect("No default input device available"),
+        false => {
+            let host = cpal::host_from_id(
+                match config
+                    .device_config
+                    .host
+                    .clone()
+                    .expect("Host not specified")
+                    .to_lowercase()
+                    .as_str()
+                {
+                    "asio" => Asio,
+                    "wasapi" => Wasapi,
+                    _ => panic!("Invalid host"),
+                },
+            )
+            .unwrap();
+
+            host.input_devices()
+                .unwrap()
+                .find(|device| {
+                    device.name().unwrap()
+                        == config
+                            .device_config
+                            .device_name
+                            .clone()
+                            .expect("Device name not specified")
+                })
+                .expect("Couldn't find device")
+        }
+    }
+}
+
+/// Walks a short-time energy envelope of `samples` and cuts a slice around every onset: a hop
+/// whose energy jumps above the (slowly-adapting) noise floor by `ONSET_THRESHOLD_MULTIPLIER`.
+fn slice_onsets(samples: &[f32], sample_rate: f32) -> Vec<Vec<f32>> {
+    let hop_size = ((sample_rate * ENVELOPE_HOP_MS / 1000.0) as usize).max(1);
+
+    if samples.is_empty() {
+        return Vec::new();
+    }
+
+    let envelope: Vec<f32> = samples
+        .chunks(hop_size)
+        .map(|chunk| chunk.iter().map(|sample| sample * sample).sum::<f32>() / chunk.len() as f32)
+        .collect();
+
+    let mut noise_floor = envelope.first().copied().unwrap_or(0.0).max(f32::EPSILON);
+    let mut slices = Vec::new();
+    let mut hop_index = 0;
+
+    while hop_index < envelope.len() {
+        if envelope[hop_index] > noise_floor * ONSET_THRESHOLD_MULTIPLIER {
+            let onset_sample = hop_index * hop_size;
+            let start = onset_sample.saturating_sub((sample_rate * PRE_ONSET_MS / 1000.0) as usize);
+
+            // Trim the slice's tail once the envelope falls back below the onset threshold.
+            let mut trailing_hop = hop_index;
+            while trailing_hop < envelope.len()
+                && envelope[trailing_hop] > noise_floor * ONSET_THRESHOLD_MULTIPLIER
+            {
+                trailing_hop += 1;
+            }
+
+            let max_end = onset_sample + (sample_rate * MAX_SLICE_MS / 1000.0) as usize;
+            let end = (trailing_hop * hop_size).min(max_end).min(samples.len());
+
+            if end > start {
+                slices.push(normalize_peak(&samples[start..end]));
+            }
+
+            hop_index = trailing_hop.max(hop_index + 1);
+        } else {
+            noise_floor = noise_floor * 0.99 + envelope[hop_index] * 0.01;
+            hop_index += 1;
+        }
+    }
+
+    slices
+}
+
+/// Scales `samples` so their peak absolute amplitude sits at `TARGET_PEAK_DBFS`.
+fn normalize_peak(samples: &[f32]) -> Vec<f32> {
+    let peak = samples.iter().fold(0.0_f32, |max, sample| max.max(sample.abs()));
+
+    if peak <= f32::EPSILON {
+        return samples.to_vec();
+    }
+
+    let gain = 10f32.powf(TARGET_PEAK_DBFS / 20.0) / peak;
+
+    samples.iter().map(|sample| sample * gain).collect()
+}
+
+/// Writes `samples` (already downmixed to mono by `run`) as a mono WAV file.
+fn write_wav(path: &Path, samples: &[f32], sample_rate: u32) {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+
+    let mut writer = match WavWriter::create(path, spec) {
+        Ok(writer) => writer,
+        Err(error) => {
+            error!("Couldn't create {:?}: {}", path, error);
+            return;
+        }
+    };
+
+    for sample in samples {
+        if let Err(error) = writer.write_sample(*sample) {
+            error!("Failed to write sample to {:?}: {}", path, error);
+            return;
+        }
+    }
+
+    if let Err(error) = writer.finalize() {
+        error!("Failed to finalize {:?}: {}", path, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_SAMPLE_RATE: f32 = 1000.0;
+
+    /// Builds a buffer of low-amplitude noise with a few loud "clicks" spliced in at the given
+    /// sample offsets, mimicking a short recording of a few keypresses.
+    fn samples_with_clicks(total_samples: usize, click_starts: &[usize], click_len: usize) -> Vec<f32> {
+        let mut samples: Vec<f32> = (0..total_samples)
+            .map(|index| if index % 2 == 0 { 0.01 } else { -0.01 })
+            .collect();
+
+        for &start in click_starts {
+            for offset in 0..click_len {
+                if let Some(sample) = samples.get_mut(start + offset) {
+                    *sample = if offset % 2 == 0 { 0.9 } else { -0.9 };
+                }
+            }
+        }
+
+        samples
+    }
+
+    #[test]
+    fn slice_onsets_finds_one_slice_per_click() {
+        let samples = samples_with_clicks(2000, &[500, 1500], 5);
+
+        let slices = slice_onsets(&samples, TEST_SAMPLE_RATE);
+
+        assert_eq!(slices.len(), 2);
+    }
+
+    #[test]
+    fn slice_onsets_includes_pre_onset_padding() {
+        let click_len = 5;
+        let samples = samples_with_clicks(2000, &[500], click_len);
+
+        let slices = slice_onsets(&samples, TEST_SAMPLE_RATE);
+
+        assert_eq!(slices.len(), 1);
+        // The slice should cover the click's own hop plus `PRE_ONSET_MS` before the detected
+        // onset.
+        let hop_size = (TEST_SAMPLE_RATE * ENVELOPE_HOP_MS / 1000.0) as usize;
+        let pre_onset = (TEST_SAMPLE_RATE * PRE_ONSET_MS / 1000.0) as usize;
+        assert_eq!(slices[0].len(), hop_size + pre_onset);
+    }
+
+    #[test]
+    fn slice_onsets_ignores_silence() {
+        let samples = vec![0.0; 2000];
+
+        let slices = slice_onsets(&samples, TEST_SAMPLE_RATE);
+
+        assert!(slices.is_empty());
+    }
+
+    #[test]
+    fn slice_onsets_handles_empty_input() {
+        assert!(slice_onsets(&[], TEST_SAMPLE_RATE).is_empty());
+    }
+
+    #[test]
+    fn normalize_peak_scales_to_target_dbfs() {
+        let samples = vec![0.1, -0.5, 0.25];
+
+        let normalized = normalize_peak(&samples);
+
+        let peak = normalized.iter().fold(0.0_f32, |max, sample| max.max(sample.abs()));
+        let expected_peak = 10f32.powf(TARGET_PEAK_DBFS / 20.0);
+
+        assert!((peak - expected_peak).abs() < 1e-4);
+    }
+
+    #[test]
+    fn normalize_peak_leaves_silence_untouched() {
+        let samples = vec![0.0, 0.0, 0.0];
+
+        assert_eq!(normalize_peak(&samples), samples);
+    }
+
+    #[test]
+    fn downmix_to_mono_averages_interleaved_channels() {
+        let stereo = vec![1.0, -1.0, 0.5, 0.5];
+
+        assert_eq!(downmix_to_mono(&stereo, 2), vec![0.0, 0.5]);
+    }
+
+    #[test]
+    fn downmix_to_mono_is_a_no_op_for_mono_input() {
+        let mono = vec![0.1, 0.2, 0.3];
+
+        assert_eq!(downmix_to_mono(&mono, 1), mono);
+    }
+}